@@ -0,0 +1,136 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+
+use crate::header::HeaderMap;
+
+/// Size hint for a body, used to decide `Content-Length` vs. chunked vs.
+/// no framing at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodySize {
+    None,
+    Empty,
+    Sized(u64),
+    Stream,
+}
+
+/// A streaming request/response body, optionally followed by a trailer
+/// block (e.g. gRPC status trailers) once its data is exhausted.
+pub trait MessageBody {
+    type Error;
+
+    fn size(&self) -> BodySize;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>>;
+
+    /// Poll for a trailer block to send once the data stream is exhausted.
+    ///
+    /// Bodies that don't carry trailers — the overwhelming majority — can
+    /// rely on the default, which reports none.
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<HeaderMap, Self::Error>>> {
+        Poll::Ready(None)
+    }
+}
+
+impl MessageBody for Bytes {
+    type Error = std::convert::Infallible;
+
+    fn size(&self) -> BodySize {
+        BodySize::Sized(self.len() as u64)
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        if self.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Ready(Some(Ok(std::mem::take(self.get_mut()))))
+        }
+    }
+}
+
+/// Wraps a body, appending a fixed trailer block once its data is
+/// exhausted — e.g. gRPC's trailing `grpc-status`/`grpc-message`.
+pub struct BodyWithTrailers<B> {
+    body: B,
+    trailers: Option<HeaderMap>,
+}
+
+impl<B> BodyWithTrailers<B> {
+    pub fn new(body: B, trailers: HeaderMap) -> Self {
+        BodyWithTrailers {
+            body,
+            trailers: Some(trailers),
+        }
+    }
+}
+
+impl<B> MessageBody for BodyWithTrailers<B>
+where
+    B: MessageBody + Unpin,
+{
+    type Error = B::Error;
+
+    fn size(&self) -> BodySize {
+        // Always `Stream`, even when the wrapped body is `Sized`/`Empty`:
+        // callers gate the immediate `end_stream`-on-send_request path on
+        // `BodySize`, and doing that here would close the H2 stream before
+        // `poll_trailers` ever got a chance to run, silently dropping the
+        // trailers this type exists to carry.
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        Pin::new(&mut this.body).poll_next(cx)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<HeaderMap, Self::Error>>> {
+        Poll::Ready(self.get_mut().trailers.take().map(Ok))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future::poll_fn;
+    use futures_util::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn reports_stream_size_even_when_inner_is_sized() {
+        let body = BodyWithTrailers::new(Bytes::from_static(b"hi"), HeaderMap::new());
+        assert_eq!(body.size(), BodySize::Stream);
+    }
+
+    #[test]
+    fn emits_trailers_exactly_once() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert(
+            http::header::HeaderName::from_static("grpc-status"),
+            http::HeaderValue::from_static("0"),
+        );
+        let mut body = Box::pin(BodyWithTrailers::new(Bytes::from_static(b"hi"), trailers));
+
+        let first = poll_fn(|cx| body.as_mut().poll_trailers(cx)).now_or_never().unwrap();
+        assert!(first.unwrap().is_ok());
+
+        let second = poll_fn(|cx| body.as_mut().poll_trailers(cx)).now_or_never().unwrap();
+        assert!(second.is_none());
+    }
+}
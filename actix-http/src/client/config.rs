@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+const DEFAULT_WINDOW_SIZE: u32 = 1 << 16;
+const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Tuning knobs for client connections, threaded through the H2 handshake
+/// and the connection pool.
+#[derive(Debug, Clone)]
+pub struct ConnectorConfig {
+    pub(crate) stream_window_size: u32,
+    pub(crate) conn_window_size: u32,
+    pub(crate) adaptive_window_max: Option<u32>,
+    pub(crate) http2_keep_alive_interval: Option<Duration>,
+    pub(crate) http2_keep_alive_timeout: Duration,
+    pub(crate) max_concurrent_streams: Option<usize>,
+}
+
+impl Default for ConnectorConfig {
+    fn default() -> Self {
+        ConnectorConfig {
+            stream_window_size: DEFAULT_WINDOW_SIZE,
+            conn_window_size: DEFAULT_WINDOW_SIZE,
+            adaptive_window_max: None,
+            http2_keep_alive_interval: None,
+            http2_keep_alive_timeout: DEFAULT_KEEP_ALIVE_TIMEOUT,
+            max_concurrent_streams: None,
+        }
+    }
+}
+
+impl ConnectorConfig {
+    /// Enable adaptive HTTP/2 flow-control windows: instead of pinning the
+    /// connection/stream windows to `stream_window_size`/`conn_window_size`,
+    /// grow them to match the measured bandwidth-delay product, up to `max`.
+    pub fn adaptive_window(&mut self, max: u32) -> &mut Self {
+        self.adaptive_window_max = Some(max.max(self.stream_window_size));
+        self
+    }
+
+    /// Send an H2 PING on this interval so a dead peer (NAT timeout,
+    /// half-open TCP) is caught while a connection sits in the pool, rather
+    /// than on the next request dispatched to it.
+    pub fn http2_keep_alive_interval(&mut self, interval: Duration) -> &mut Self {
+        self.http2_keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// How long to wait for a keep-alive PING's ACK before treating the
+    /// connection as dead.
+    pub fn http2_keep_alive_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.http2_keep_alive_timeout = timeout;
+        self
+    }
+
+    /// Cap the number of streams this client keeps in flight on a single
+    /// connection, independent of (and never above) the peer's advertised
+    /// `SETTINGS_MAX_CONCURRENT_STREAMS` — which `h2`'s own `poll_ready`
+    /// already enforces on top of this.
+    pub fn max_concurrent_streams(&mut self, max: usize) -> &mut Self {
+        self.max_concurrent_streams = Some(max);
+        self
+    }
+}
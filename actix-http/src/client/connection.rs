@@ -0,0 +1,355 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time;
+
+use actix_codec::{AsyncRead, AsyncWrite};
+use bytes::Bytes;
+use h2::client::SendRequest;
+
+use crate::body::MessageBody;
+use crate::message::{RequestHeadType, ResponseHead};
+use crate::payload::Payload;
+
+use super::config::ConnectorConfig;
+use super::error::SendRequestError;
+use super::h2proto::{self, BdpEstimator, H2Response, KeepAlive, StreamPriority, Tunnel};
+use super::pool::Acquired;
+
+/// Either half of a connection checked out of the pool.
+pub(crate) enum ConnectionType<T> {
+    H1(T),
+    H2(H2Connection),
+}
+
+/// A checked-out HTTP/2 connection: the request-sending handle, plus local
+/// bookkeeping that doesn't belong to `h2` itself (the stream-slot gate, a
+/// handle to the connection's BDP estimator for per-response byte counts).
+pub(crate) struct H2Connection {
+    io: SendRequest<Bytes>,
+    slots: Arc<StreamSlots>,
+    bdp: Option<Arc<Mutex<BdpEstimator>>>,
+}
+
+impl H2Connection {
+    pub(crate) fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), h2::Error>> {
+        self.io.poll_ready(cx)
+    }
+
+    pub(crate) fn send_request(
+        &mut self,
+        req: http::Request<()>,
+        eof: bool,
+    ) -> Result<(h2::client::ResponseFuture, h2::SendStream<Bytes>), h2::Error> {
+        self.io.send_request(req, eof)
+    }
+
+    /// Wait for a local stream slot, honoring
+    /// `ConnectorConfig::max_concurrent_streams`; released in priority
+    /// order as slots free up (see [`StreamSlots`]).
+    pub(crate) async fn acquire_stream_slot(&self, priority: Option<StreamPriority>) -> StreamSlotGuard {
+        self.slots.clone().acquire(priority.unwrap_or_default()).await
+    }
+
+    /// A handle to this connection's BDP estimator, if adaptive windows are
+    /// enabled, so the response body can report bytes received back to it.
+    pub(crate) fn bdp_handle(&self) -> Option<Arc<Mutex<BdpEstimator>>> {
+        self.bdp.clone()
+    }
+}
+
+/// Complete the H2 handshake, build the adaptive-window/keep-alive state
+/// `config` asks for, and spawn the task that drives the connection's I/O
+/// alongside those background features.
+pub(crate) async fn establish<Io>(io: Io, config: &ConnectorConfig) -> Result<H2Connection, h2::Error>
+where
+    Io: AsyncRead + AsyncWrite + Unpin + 'static,
+{
+    let (send, connection) = h2proto::handshake(io, config).await?;
+
+    let bdp = config
+        .adaptive_window_max
+        .map(|max| Arc::new(Mutex::new(BdpEstimator::new(config.stream_window_size, max))));
+
+    let keep_alive = config
+        .http2_keep_alive_interval
+        .map(|interval| KeepAlive::new(interval, config.http2_keep_alive_timeout));
+
+    actix_rt::spawn(h2proto::drive_connection(connection, bdp.clone(), keep_alive));
+
+    Ok(H2Connection {
+        io: send,
+        slots: Arc::new(StreamSlots::new(config.max_concurrent_streams)),
+        bdp,
+    })
+}
+
+/// Send a normal (non-tunnel) request and return its response head and body.
+pub(crate) async fn send_request<T, B>(
+    io: H2Connection,
+    head: RequestHeadType,
+    body: B,
+    created: time::Instant,
+    acquired: Acquired<T>,
+) -> Result<(ResponseHead, Payload), SendRequestError>
+where
+    T: AsyncRead + AsyncWrite + Unpin + 'static,
+    B: MessageBody,
+{
+    match h2proto::send_request(io, head, body, created, acquired).await? {
+        H2Response::Payload(head, payload) => Ok((head, payload)),
+        H2Response::Tunnel(..) => Err(SendRequestError::UnexpectedResponseShape),
+    }
+}
+
+/// Open an HTTP/2 extended-CONNECT tunnel (the request's method must be
+/// `CONNECT`) and return its response head plus the full-duplex [`Tunnel`].
+pub(crate) async fn send_connect_request<T, B>(
+    io: H2Connection,
+    head: RequestHeadType,
+    body: B,
+    created: time::Instant,
+    acquired: Acquired<T>,
+) -> Result<(ResponseHead, Tunnel), SendRequestError>
+where
+    T: AsyncRead + AsyncWrite + Unpin + 'static,
+    B: MessageBody,
+{
+    match h2proto::send_request(io, head, body, created, acquired).await? {
+        H2Response::Tunnel(head, tunnel) => Ok((head, tunnel)),
+        H2Response::Payload(..) => Err(SendRequestError::UnexpectedResponseShape),
+    }
+}
+
+/// Caps a connection's in-flight streams at
+/// `ConnectorConfig::max_concurrent_streams`, independent of (and never
+/// above) the peer's advertised `SETTINGS_MAX_CONCURRENT_STREAMS`, which
+/// `h2`'s own `poll_ready` already enforces on top of this.
+///
+/// Waiters are released in priority order — highest `weight` first,
+/// `exclusive` ahead of all others, ties broken by arrival order — rather
+/// than plain FIFO, so a burst of requests on one connection gets local
+/// ordering control instead of competing unordered.
+pub(crate) struct StreamSlots {
+    max: Option<usize>,
+    inner: Mutex<StreamSlotsInner>,
+}
+
+struct StreamSlotsInner {
+    in_use: usize,
+    next_seq: u64,
+    queue: BinaryHeap<QueuedWaiter>,
+    wakers: HashMap<u64, Waker>,
+    ready: HashSet<u64>,
+    /// Waiters whose `acquire` future was dropped while still queued;
+    /// `release` skips these instead of granting them a slot. `BinaryHeap`
+    /// has no cheap arbitrary-element removal, so cancelled entries are
+    /// tombstoned here and filtered out lazily as they're popped.
+    cancelled: HashSet<u64>,
+}
+
+struct QueuedWaiter {
+    rank: u16,
+    seq: u64,
+}
+
+impl PartialEq for QueuedWaiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank == other.rank && self.seq == other.seq
+    }
+}
+impl Eq for QueuedWaiter {}
+
+impl PartialOrd for QueuedWaiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedWaiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // `BinaryHeap` is a max-heap: higher rank wins, and among equal
+        // ranks the lower (earlier) sequence number wins, so reverse it.
+        self.rank.cmp(&other.rank).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl StreamSlots {
+    pub(crate) fn new(max: Option<usize>) -> Self {
+        StreamSlots {
+            max,
+            inner: Mutex::new(StreamSlotsInner {
+                in_use: 0,
+                next_seq: 0,
+                queue: BinaryHeap::new(),
+                wakers: HashMap::new(),
+                ready: HashSet::new(),
+                cancelled: HashSet::new(),
+            }),
+        }
+    }
+
+    async fn acquire(self: Arc<Self>, priority: StreamPriority) -> StreamSlotGuard {
+        let Some(max) = self.max else {
+            return StreamSlotGuard {
+                slots: self,
+                counted: false,
+            };
+        };
+
+        let seq = {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.in_use < max {
+                inner.in_use += 1;
+                None
+            } else {
+                let seq = inner.next_seq;
+                inner.next_seq += 1;
+                let rank = if priority.exclusive { u16::MAX } else { priority.weight as u16 };
+                inner.queue.push(QueuedWaiter { rank, seq });
+                Some(seq)
+            }
+        };
+
+        if let Some(seq) = seq {
+            // guards the queued-but-not-yet-granted window: if this future
+            // is dropped (e.g. the request timed out) before `poll_turn`
+            // resolves, its `Drop` cancels the waiter so a slot it was
+            // never granted isn't later handed out to nobody, and so a
+            // slot it *was* granted (raced with a concurrent `release`)
+            // is handed back instead of leaking out of `in_use` forever.
+            let mut cancel_on_drop = CancelOnDrop {
+                slots: &*self,
+                seq,
+                armed: true,
+            };
+            poll_fn(|cx| self.poll_turn(seq, cx)).await;
+            cancel_on_drop.armed = false;
+        }
+
+        StreamSlotGuard {
+            slots: self,
+            counted: true,
+        }
+    }
+
+    fn poll_turn(&self, seq: u64, cx: &mut Context<'_>) -> Poll<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.ready.remove(&seq) {
+            return Poll::Ready(());
+        }
+        inner.wakers.insert(seq, cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// A queued waiter was dropped before claiming its turn: if it had
+    /// already been granted a slot (raced with a concurrent `release`),
+    /// hand that slot back; otherwise just drop its queue/waker entries so
+    /// a later `release` doesn't grant the slot to an abandoned waiter.
+    fn cancel(&self, seq: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.wakers.remove(&seq);
+        if inner.ready.remove(&seq) {
+            drop(inner);
+            self.release();
+        } else {
+            inner.cancelled.insert(seq);
+        }
+    }
+
+    fn release(&self) {
+        let Some(max) = self.max else { return };
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.in_use = inner.in_use.saturating_sub(1);
+        while inner.in_use < max {
+            let Some(next) = inner.queue.pop() else {
+                break;
+            };
+            if inner.cancelled.remove(&next.seq) {
+                // waiter was cancelled while queued; skip it without
+                // granting a slot that no one will ever release.
+                continue;
+            }
+            inner.in_use += 1;
+            inner.ready.insert(next.seq);
+            if let Some(waker) = inner.wakers.remove(&next.seq) {
+                waker.wake();
+            }
+            break;
+        }
+    }
+}
+
+/// Cancels a queued [`StreamSlots`] waiter on drop unless disarmed — see the
+/// comment at its construction site in `acquire`.
+struct CancelOnDrop<'a> {
+    slots: &'a StreamSlots,
+    seq: u64,
+    armed: bool,
+}
+
+impl Drop for CancelOnDrop<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            self.slots.cancel(self.seq);
+        }
+    }
+}
+
+pub(crate) struct StreamSlotGuard {
+    slots: Arc<StreamSlots>,
+    counted: bool,
+}
+
+impl Drop for StreamSlotGuard {
+    fn drop(&mut self) {
+        if self.counted {
+            self.slots.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn queue_pops_highest_priority_first_ties_broken_by_arrival() {
+        let mut heap = BinaryHeap::new();
+        heap.push(QueuedWaiter { rank: 5, seq: 2 });
+        heap.push(QueuedWaiter { rank: 10, seq: 1 });
+        heap.push(QueuedWaiter { rank: 10, seq: 0 });
+
+        assert_eq!(heap.pop().map(|w| w.seq), Some(0));
+        assert_eq!(heap.pop().map(|w| w.seq), Some(1));
+        assert_eq!(heap.pop().map(|w| w.seq), Some(2));
+    }
+
+    #[actix_rt::test]
+    async fn a_cancelled_waiter_does_not_leak_its_slot() {
+        let slots = Arc::new(StreamSlots::new(Some(1)));
+
+        let guard = slots.clone().acquire(StreamPriority::default()).await;
+
+        // with the one slot taken, this acquire queues; poll it once so it
+        // registers as a waiter, then drop it without ever completing --
+        // exactly what happens when a request times out while queued.
+        let queued = slots.clone().acquire(StreamPriority::default());
+        assert!(queued.now_or_never().is_none());
+
+        drop(guard);
+
+        // the freed slot must go to a *new* acquire, not be silently
+        // swallowed by the cancelled one.
+        assert!(slots
+            .clone()
+            .acquire(StreamPriority::default())
+            .now_or_never()
+            .is_some());
+    }
+}
@@ -0,0 +1,46 @@
+use std::{fmt, io};
+
+/// Errors that can occur while sending a request and waiting for a response
+/// head over an established connection.
+#[derive(Debug)]
+pub enum SendRequestError {
+    Http2(h2::Error),
+    Io(io::Error),
+    /// A caller used [`send_request`](super::connection::send_request) on a
+    /// stream that turned out to be an extended-CONNECT tunnel, or
+    /// [`send_connect_request`](super::connection::send_connect_request) on
+    /// one that wasn't.
+    UnexpectedResponseShape,
+}
+
+impl fmt::Display for SendRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendRequestError::Http2(e) => write!(f, "h2 error: {}", e),
+            SendRequestError::Io(e) => write!(f, "io error: {}", e),
+            SendRequestError::UnexpectedResponseShape => {
+                write!(f, "response was not the shape the caller expected (tunnel vs. normal)")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SendRequestError {}
+
+impl From<h2::Error> for SendRequestError {
+    fn from(e: h2::Error) -> Self {
+        SendRequestError::Http2(e)
+    }
+}
+
+impl From<io::Error> for SendRequestError {
+    fn from(e: io::Error) -> Self {
+        SendRequestError::Io(e)
+    }
+}
+
+impl From<std::convert::Infallible> for SendRequestError {
+    fn from(e: std::convert::Infallible) -> Self {
+        match e {}
+    }
+}
@@ -1,14 +1,17 @@
 use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
 use std::time;
 
 use actix_codec::{AsyncRead, AsyncWrite};
 use bytes::Bytes;
 use futures_util::future::poll_fn;
 use h2::{
-    client::{Builder, Connection, SendRequest},
+    client::{Builder, Connection, PingPong, SendRequest},
     SendStream,
 };
-use http::header::{HeaderValue, CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING};
+use http::header::{HeaderValue, CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING, UPGRADE};
 use http::{request::Request, Method, Version};
 
 use crate::body::{BodySize, MessageBody};
@@ -22,31 +25,81 @@ use super::error::SendRequestError;
 use super::pool::Acquired;
 use crate::client::connection::H2Connection;
 
+/// The result of [`send_request`]: either a normal response with its body,
+/// or — for an extended-CONNECT request — the response head plus a
+/// [`Tunnel`] bundling both halves of the now-opened byte stream.
+pub(crate) enum H2Response {
+    Payload(ResponseHead, Payload),
+    Tunnel(ResponseHead, Tunnel),
+}
+
+/// A full-duplex HTTP/2 extended-CONNECT (RFC 8441) tunnel: the write half
+/// is the request stream's remaining capacity, the read half is the
+/// response body. Used for WebSocket-over-H2 and generic proxy tunneling.
+pub(crate) struct Tunnel {
+    send: SendStream<Bytes>,
+    recv: Payload,
+}
+
+impl Tunnel {
+    pub(crate) fn into_parts(self) -> (SendStream<Bytes>, Payload) {
+        (self.send, self.recv)
+    }
+}
+
 pub(crate) async fn send_request<T, B>(
     mut io: H2Connection,
     head: RequestHeadType,
     body: B,
     created: time::Instant,
     acquired: Acquired<T>,
-) -> Result<(ResponseHead, Payload), SendRequestError>
+) -> Result<H2Response, SendRequestError>
 where
     T: AsyncRead + AsyncWrite + Unpin + 'static,
     B: MessageBody,
 {
     trace!("Sending client request: {:?} {:?}", head, body.size());
 
+    // extended CONNECT (RFC 8441) opens a full-duplex tunnel: no body is
+    // framed as request data, and the stream is kept half-open (no
+    // `end_stream` on the request) so the caller can write to it.
+    let is_connect = head.as_ref().method == Method::CONNECT;
+
     let head_req = head.as_ref().method == Method::HEAD;
     let length = body.size();
-    let eof = matches!(
-        length,
-        BodySize::None | BodySize::Empty | BodySize::Sized(0)
-    );
+    let eof = !is_connect
+        && matches!(
+            length,
+            BodySize::None | BodySize::Empty | BodySize::Sized(0)
+        );
 
     let mut req = Request::new(());
-    *req.uri_mut() = head.as_ref().uri.clone();
     *req.method_mut() = head.as_ref().method.clone();
     *req.version_mut() = Version::HTTP_2;
 
+    // per-request HTTP/2 stream-scheduling hint, threaded through the
+    // caller via `RequestHeadType`'s extensions
+    let priority = head.as_ref().extensions.get::<StreamPriority>().copied();
+
+    if is_connect {
+        // normal CONNECT carries only `:authority`; extended CONNECT for
+        // protocols like WebSocket additionally carries `:protocol`, which
+        // we derive from the `Upgrade` header the caller would otherwise
+        // have sent for an HTTP/1.1 upgrade.
+        let mut parts = http::uri::Parts::default();
+        parts.authority = head.as_ref().uri.authority().cloned();
+        *req.uri_mut() = http::Uri::from_parts(parts).unwrap_or_default();
+
+        if let Some(upgrade) = head.as_ref().headers.get(UPGRADE) {
+            if let Ok(upgrade) = upgrade.to_str() {
+                req.extensions_mut()
+                    .insert(h2::ext::Protocol::from(upgrade));
+            }
+        }
+    } else {
+        *req.uri_mut() = head.as_ref().uri.clone();
+    }
+
     let mut skip_len = true;
     // let mut has_date = false;
 
@@ -92,8 +145,10 @@ where
         match *key {
             // TODO: consider skipping other headers according to:
             //       https://tools.ietf.org/html/rfc7540#section-8.1.2.2
-            // omit HTTP/1.x only headers
-            CONNECTION | TRANSFER_ENCODING => continue,
+            // omit HTTP/1.x only headers; `Upgrade` is consumed above to
+            // derive the extended-CONNECT `:protocol` pseudo-header and must
+            // not also be forwarded as a regular header over H2
+            CONNECTION | TRANSFER_ENCODING | UPGRADE => continue,
             CONTENT_LENGTH if skip_len => continue,
             // DATE => has_date = true,
             _ => {}
@@ -101,20 +156,32 @@ where
         req.headers_mut().append(key, value.clone());
     }
 
+    // gate on a local stream slot so a burst of requests on one connection
+    // queues behind `ConnectorConfig::max_concurrent_streams` instead of
+    // overrunning what the peer actually advertised; higher-weight/exclusive
+    // requests are woken ahead of queued peers with the same slot budget.
+    let _slot = io.acquire_stream_slot(priority).await;
+
     let res = poll_fn(|cx| io.poll_ready(cx)).await;
     if let Err(e) = res {
         release(io, acquired, created, e.is_io());
         return Err(SendRequestError::from(e));
     }
 
-    let resp = match io.send_request(req, eof) {
+    let bdp_handle = io.bdp_handle();
+
+    let (resp, tunnel_send) = match io.send_request(req, eof) {
         Ok((fut, send)) => {
             release(io, acquired, created, false);
 
-            if !eof {
-                send_body(body, send).await?;
+            if is_connect {
+                (fut.await.map_err(SendRequestError::from)?, Some(send))
+            } else {
+                if !eof {
+                    send_body(body, send).await?;
+                }
+                (fut.await.map_err(SendRequestError::from)?, None)
             }
-            fut.await.map_err(SendRequestError::from)?
         }
         Err(e) => {
             release(io, acquired, created, e.is_io());
@@ -123,12 +190,34 @@ where
     };
 
     let (parts, body) = resp.into_parts();
-    let payload = if head_req { Payload::None } else { body.into() };
 
     let mut head = ResponseHead::new(parts.status);
     head.version = parts.version;
     head.headers = parts.headers.into();
-    Ok((head, payload))
+
+    match tunnel_send {
+        Some(send) => Ok(H2Response::Tunnel(
+            head,
+            Tunnel {
+                send,
+                recv: body.into(),
+            },
+        )),
+        None => {
+            let payload = if head_req {
+                Payload::None
+            } else {
+                let payload: Payload = body.into();
+                match bdp_handle {
+                    Some(bdp) => {
+                        payload.counted(Arc::new(move |len| bdp.lock().unwrap().record_data(len)))
+                    }
+                    None => payload,
+                }
+            };
+            Ok(H2Response::Payload(head, payload))
+        }
+    }
 }
 
 async fn send_body<B: MessageBody>(
@@ -145,13 +234,7 @@ async fn send_body<B: MessageBody>(
                     buf = Some(b);
                 }
                 Some(Err(e)) => return Err(e.into()),
-                None => {
-                    if let Err(e) = send.send_data(Bytes::new(), true) {
-                        return Err(e.into());
-                    }
-                    send.reserve_capacity(0);
-                    return Ok(());
-                }
+                None => return close_stream(body.as_mut(), send).await,
             }
         }
 
@@ -178,6 +261,29 @@ async fn send_body<B: MessageBody>(
     }
 }
 
+/// Finish the stream once the body's data frames are exhausted.
+///
+/// Gives the body a chance to produce a trailer block (e.g. gRPC status
+/// trailers) before the stream is closed; falls back to an empty,
+/// `end_stream`-flagged data frame when the body has none.
+async fn close_stream<B: MessageBody>(
+    mut body: std::pin::Pin<&mut B>,
+    mut send: SendStream<Bytes>,
+) -> Result<(), SendRequestError> {
+    match poll_fn(|cx| body.as_mut().poll_trailers(cx)).await {
+        Some(Ok(trailers)) => {
+            send.reserve_capacity(0);
+            send.send_trailers(trailers.into())?;
+        }
+        Some(Err(e)) => return Err(e.into()),
+        None => {
+            send.send_data(Bytes::new(), true)?;
+            send.reserve_capacity(0);
+        }
+    }
+    Ok(())
+}
+
 /// release SendRequest object
 fn release<T: AsyncRead + AsyncWrite + Unpin + 'static>(
     io: H2Connection,
@@ -203,6 +309,352 @@ where
     builder
         .initial_window_size(config.stream_window_size)
         .initial_connection_window_size(config.conn_window_size)
-        .enable_push(false);
+        .enable_push(false)
+        // advertise SETTINGS_ENABLE_CONNECT_PROTOCOL so extended CONNECT
+        // (RFC 8441) tunnels, e.g. WebSocket-over-H2, can be negotiated
+        .enable_connect_protocol();
     builder.handshake(io)
 }
+
+/// Interval between BDP samples while a connection has an adaptive window.
+pub(crate) const BDP_SAMPLE_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+/// Estimates the bandwidth-delay product of a connection and grows its
+/// HTTP/2 flow-control windows to match, up to `ConnectorConfig`'s
+/// configured ceiling.
+///
+/// Driven by [`drive_connection`]: a sampling round starts with a
+/// timestamped PING (`maybe_sample`); while it is in flight `record_data`
+/// accumulates the bytes the connection receives. When the PING ACK comes
+/// back, `record_ack` turns `(bytes, rtt)` into a new target window size,
+/// smoothing RTT and only ever growing the window.
+pub(crate) struct BdpEstimator {
+    max_window: u32,
+    window: u32,
+    bytes: usize,
+    sent_at: Option<time::Instant>,
+    srtt: Option<time::Duration>,
+}
+
+impl BdpEstimator {
+    pub(crate) fn new(initial_window: u32, max_window: u32) -> Self {
+        BdpEstimator {
+            max_window,
+            window: initial_window,
+            bytes: 0,
+            sent_at: None,
+            srtt: None,
+        }
+    }
+
+    /// Start a new sampling round if one isn't already in flight. Returns
+    /// `true` if this call sent the PING, so the caller can track whose
+    /// sample currently owns the connection's one in-flight PING slot.
+    pub(crate) fn maybe_sample(&mut self, ping_pong: &mut PingPong) -> bool {
+        if self.sent_at.is_some() {
+            return false;
+        }
+        if ping_pong.send_ping(h2::Ping::opaque()).is_ok() {
+            self.sent_at = Some(time::Instant::now());
+            self.bytes = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Record data received on the connection while a sample is in flight.
+    pub(crate) fn record_data(&mut self, len: usize) {
+        if self.sent_at.is_some() {
+            self.bytes += len;
+        }
+    }
+
+    /// The PING ACK for the current sample arrived; fold it into the
+    /// smoothed RTT and grow the window toward the measured BDP.
+    ///
+    /// Returns the new window size when it grew, so the caller can push it
+    /// to the connection (and its streams) via `set_target_window_size`.
+    pub(crate) fn record_ack(&mut self) -> Option<u32> {
+        let sent_at = self.sent_at.take()?;
+        let rtt = sent_at.elapsed();
+        let srtt = match self.srtt {
+            Some(srtt) => srtt / 8 * 7 + rtt / 8,
+            None => rtt,
+        };
+        self.srtt = Some(srtt);
+
+        if rtt.as_secs_f64() <= 0.0 {
+            return None;
+        }
+        let bandwidth = self.bytes as f64 / rtt.as_secs_f64();
+        let target = compute_target_window(bandwidth, srtt, self.window, self.max_window);
+        if target > self.window {
+            self.window = target;
+            Some(target)
+        } else {
+            None
+        }
+    }
+}
+
+/// BDP (`bandwidth * srtt`) rounded up to the next power of two, clamped to
+/// `[current, max]`.
+///
+/// Clamping before `next_power_of_two`/the `u32` cast matters: a `u64` BDP
+/// from a fast, bursty sample can otherwise overflow `next_power_of_two`
+/// (panics in debug) or get silently truncated by `as u32`. Pulled out of
+/// `record_ack` so the window math can be tested without a live `PingPong`.
+fn compute_target_window(bandwidth: f64, srtt: time::Duration, current: u32, max: u32) -> u32 {
+    let bdp = ((bandwidth * srtt.as_secs_f64()) as u64).min(max as u64);
+    (bdp.next_power_of_two() as u32).clamp(current, max)
+}
+
+/// `h2`'s `PingPong` only ever has one PING in flight at a time, so BDP
+/// sampling and keep-alive — which share a connection's single `PingPong`
+/// handle — can't both have one outstanding. Tracks which of the two sent
+/// the PING currently in flight, so [`drive_connection`] knows which one a
+/// `poll_pong` ACK belongs to and can keep the other from stomping it.
+enum PingOwner {
+    Bdp,
+    KeepAlive,
+}
+
+/// Drives a connection's I/O alongside its optional BDP estimator and
+/// keep-alive reaper, applying window growth and reaping dead connections
+/// as PING ACKs and timers fire. Spawned once per connection.
+pub(crate) async fn drive_connection<Io>(
+    mut connection: Connection<Io, Bytes>,
+    bdp: Option<Arc<Mutex<BdpEstimator>>>,
+    mut keep_alive: Option<KeepAlive>,
+) where
+    Io: AsyncRead + AsyncWrite + Unpin + 'static,
+{
+    let mut ping_pong = connection.ping_pong();
+    let mut ping_owner: Option<PingOwner> = None;
+
+    let mut bdp_tick = bdp.as_ref().map(|_| actix_rt::time::interval(BDP_SAMPLE_INTERVAL));
+    let mut keep_alive_tick = keep_alive
+        .as_ref()
+        .map(|ka| actix_rt::time::interval(ka.interval));
+
+    let died = poll_fn(|cx| {
+        if let Some(ping_pong) = ping_pong.as_mut() {
+            while let Poll::Ready(Ok(_pong)) = ping_pong.poll_pong(cx) {
+                match ping_owner.take() {
+                    Some(PingOwner::Bdp) => {
+                        if let Some(bdp) = bdp.as_ref() {
+                            if let Some(new_window) = bdp.lock().unwrap().record_ack() {
+                                connection.set_target_window_size(new_window);
+                                if let Err(e) = connection.set_initial_window_size(new_window) {
+                                    trace!("failed to grow H2 stream window: {:?}", e);
+                                }
+                            }
+                        }
+                    }
+                    Some(PingOwner::KeepAlive) => {
+                        if let Some(ka) = keep_alive.as_mut() {
+                            ka.record_ack();
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        if let (Some(tick), Some(bdp), Some(ping_pong)) =
+            (bdp_tick.as_mut(), bdp.as_ref(), ping_pong.as_mut())
+        {
+            while tick.poll_tick(cx).is_ready() {
+                // a keep-alive PING already owns the connection's one
+                // in-flight slot; defer sampling to the next tick rather
+                // than sending a second PING that would stomp on it.
+                if matches!(ping_owner, Some(PingOwner::KeepAlive)) {
+                    break;
+                }
+                if bdp.lock().unwrap().maybe_sample(ping_pong) {
+                    ping_owner = Some(PingOwner::Bdp);
+                }
+            }
+        }
+
+        if let (Some(tick), Some(ka), Some(ping_pong)) =
+            (keep_alive_tick.as_mut(), keep_alive.as_mut(), ping_pong.as_mut())
+        {
+            while tick.poll_tick(cx).is_ready() {
+                if matches!(ping_owner, Some(PingOwner::Bdp)) {
+                    break;
+                }
+                if let KeepAliveState::TimedOut = ka.poll(ping_pong) {
+                    return Poll::Ready(());
+                }
+                if ka.has_ping_in_flight() {
+                    ping_owner = Some(PingOwner::KeepAlive);
+                }
+            }
+        }
+
+        match Pin::new(&mut connection).poll(cx) {
+            Poll::Ready(res) => {
+                if let Err(e) = res {
+                    trace!("h2 connection driver exiting: {:?}", e);
+                }
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    });
+
+    died.await;
+}
+
+/// Whether `elapsed` (time since the last PING) has reached `interval`.
+/// Pulled out of `KeepAlive::poll` so the timing logic can be tested without
+/// a live `PingPong`.
+fn keep_alive_due(elapsed: time::Duration, interval: time::Duration) -> bool {
+    elapsed >= interval
+}
+
+/// Outcome of polling a [`KeepAlive`] reaper.
+pub(crate) enum KeepAliveState {
+    /// Nothing to do yet.
+    Idle,
+    /// A PING ACK is overdue; the connection should be treated as dead so
+    /// `Acquired::release` evicts it from the pool instead of handing it out.
+    TimedOut,
+}
+
+/// Sends periodic H2 PINGs on a pooled connection so a dead peer (NAT
+/// timeout, half-open TCP) is caught before it's handed to a future request
+/// rather than on that request's first write.
+///
+/// Always pings on `interval` regardless of traffic: an earlier "while idle"
+/// mode that skipped the PING on an active connection was dropped because
+/// nothing in `drive_connection` can observe real request/response traffic
+/// to reset its clock against, which made it behave identically to this
+/// mode anyway on a busy connection.
+///
+/// Driven by [`drive_connection`], which owns the connection's single
+/// [`PingPong`] handle and passes it in on each `poll`.
+pub(crate) struct KeepAlive {
+    interval: time::Duration,
+    timeout: time::Duration,
+    last_ping_at: time::Instant,
+    pending_since: Option<time::Instant>,
+}
+
+impl KeepAlive {
+    pub(crate) fn new(interval: time::Duration, timeout: time::Duration) -> Self {
+        KeepAlive {
+            interval,
+            timeout,
+            last_ping_at: time::Instant::now(),
+            pending_since: None,
+        }
+    }
+
+    /// Drive the reaper: sends a PING once the connection has gone
+    /// `interval` without one, and reports `TimedOut` if an in-flight
+    /// PING's ACK hasn't arrived within `timeout`.
+    pub(crate) fn poll(&mut self, ping_pong: &mut PingPong) -> KeepAliveState {
+        if let Some(pending_since) = self.pending_since {
+            if pending_since.elapsed() >= self.timeout {
+                return KeepAliveState::TimedOut;
+            }
+            return KeepAliveState::Idle;
+        }
+
+        if !keep_alive_due(self.last_ping_at.elapsed(), self.interval) {
+            return KeepAliveState::Idle;
+        }
+
+        if ping_pong.send_ping(h2::Ping::opaque()).is_ok() {
+            let now = time::Instant::now();
+            self.pending_since = Some(now);
+            self.last_ping_at = now;
+        }
+        KeepAliveState::Idle
+    }
+
+    /// The outstanding PING's ACK arrived; clear the pending deadline.
+    pub(crate) fn record_ack(&mut self) {
+        self.pending_since = None;
+    }
+
+    /// Whether this reaper currently owns the connection's one in-flight
+    /// PING slot (used by [`drive_connection`] to keep BDP sampling from
+    /// stomping on a keep-alive PING already awaiting its ACK).
+    pub(crate) fn has_ping_in_flight(&self) -> bool {
+        self.pending_since.is_some()
+    }
+}
+
+/// HTTP/2 stream scheduling hint for a single request, set via
+/// `RequestHeadType`'s extensions.
+///
+/// `h2`'s client doesn't expose wire-level PRIORITY frames, so this only
+/// controls the order in which requests queued behind the connection's
+/// local stream-slot budget (`ConnectorConfig::max_concurrent_streams`) are
+/// released: higher `weight` goes first, `exclusive` jumps the queue
+/// entirely, ties broken in arrival order.
+///
+/// Set it by inserting one into a [`RequestHead`](crate::message::RequestHead)'s
+/// public `extensions` field before wrapping it in a `RequestHeadType` to
+/// send: `head.extensions.insert(StreamPriority::new(weight, exclusive))`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamPriority {
+    pub weight: u8,
+    pub exclusive: bool,
+}
+
+impl StreamPriority {
+    pub fn new(weight: u8, exclusive: bool) -> Self {
+        StreamPriority { weight, exclusive }
+    }
+}
+
+impl Default for StreamPriority {
+    fn default() -> Self {
+        StreamPriority {
+            weight: 127,
+            exclusive: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compute_target_window_rounds_bdp_up_to_a_power_of_two() {
+        // 10 MB/s over a 100ms smoothed RTT is a ~1MB BDP.
+        let window = compute_target_window(
+            10_000_000.0,
+            time::Duration::from_millis(100),
+            1 << 16,
+            1 << 24,
+        );
+        assert_eq!(window, 1_000_000u64.next_power_of_two() as u32);
+    }
+
+    #[test]
+    fn compute_target_window_never_exceeds_the_configured_max() {
+        let window = compute_target_window(f64::MAX, time::Duration::from_secs(1), 1 << 16, 1 << 20);
+        assert_eq!(window, 1 << 20);
+    }
+
+    #[test]
+    fn compute_target_window_never_shrinks_below_the_current_window() {
+        let window = compute_target_window(1.0, time::Duration::from_nanos(1), 1 << 16, 1 << 24);
+        assert_eq!(window, 1 << 16);
+    }
+
+    #[test]
+    fn keep_alive_due_once_interval_has_elapsed() {
+        let interval = time::Duration::from_secs(30);
+        assert!(!keep_alive_due(time::Duration::from_secs(10), interval));
+        assert!(keep_alive_due(time::Duration::from_secs(30), interval));
+        assert!(keep_alive_due(time::Duration::from_secs(31), interval));
+    }
+}
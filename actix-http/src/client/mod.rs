@@ -0,0 +1,9 @@
+mod config;
+mod connection;
+mod error;
+mod h2proto;
+mod pool;
+
+pub use config::ConnectorConfig;
+pub use error::SendRequestError;
+pub use h2proto::StreamPriority;
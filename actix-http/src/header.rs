@@ -0,0 +1,38 @@
+use std::ops::{Deref, DerefMut};
+
+/// Thin wrapper around `http::HeaderMap`, so the client module can grow
+/// conversions to/from it without running into the orphan rule.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderMap(http::HeaderMap);
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        HeaderMap(http::HeaderMap::new())
+    }
+}
+
+impl Deref for HeaderMap {
+    type Target = http::HeaderMap;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for HeaderMap {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<http::HeaderMap> for HeaderMap {
+    fn from(inner: http::HeaderMap) -> Self {
+        HeaderMap(inner)
+    }
+}
+
+impl From<HeaderMap> for http::HeaderMap {
+    fn from(headers: HeaderMap) -> Self {
+        headers.0
+    }
+}
@@ -0,0 +1,8 @@
+#[macro_use]
+extern crate log;
+
+pub mod body;
+pub mod client;
+pub mod header;
+pub mod message;
+pub mod payload;
@@ -0,0 +1,59 @@
+use http::{Extensions, Method, StatusCode, Uri, Version};
+
+use crate::header::HeaderMap;
+
+/// An outgoing request's head, carrying an `Extensions` bag for per-request
+/// hints that don't belong on the wire as headers (e.g. HTTP/2 stream
+/// priority).
+#[derive(Debug, Clone)]
+pub struct RequestHead {
+    pub uri: Uri,
+    pub method: Method,
+    pub headers: HeaderMap,
+    pub extensions: Extensions,
+}
+
+impl Default for RequestHead {
+    fn default() -> Self {
+        RequestHead {
+            uri: Uri::default(),
+            method: Method::GET,
+            headers: HeaderMap::new(),
+            extensions: Extensions::new(),
+        }
+    }
+}
+
+/// Either an owned request head, or one shared across retries/redirects
+/// plus headers computed fresh for this particular send.
+#[derive(Debug)]
+pub enum RequestHeadType {
+    Owned(RequestHead),
+    Rc(std::rc::Rc<RequestHead>, Option<HeaderMap>),
+}
+
+impl RequestHeadType {
+    pub fn as_ref(&self) -> &RequestHead {
+        match self {
+            RequestHeadType::Owned(head) => head,
+            RequestHeadType::Rc(head, _) => head,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ResponseHead {
+    pub status: StatusCode,
+    pub version: Version,
+    pub headers: HeaderMap,
+}
+
+impl ResponseHead {
+    pub fn new(status: StatusCode) -> Self {
+        ResponseHead {
+            status,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+        }
+    }
+}
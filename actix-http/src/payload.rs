@@ -0,0 +1,67 @@
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_util::Stream;
+use h2::RecvStream;
+
+/// A streaming response body.
+pub enum Payload {
+    None,
+    H2(RecvStream),
+    /// Wraps another payload, reporting the size of each yielded chunk to
+    /// an observer — e.g. a connection's BDP estimator tallying bytes
+    /// received during a sampling window.
+    Counted(Box<Payload>, Arc<dyn Fn(usize) + Send + Sync>),
+}
+
+impl From<RecvStream> for Payload {
+    fn from(recv: RecvStream) -> Self {
+        Payload::H2(recv)
+    }
+}
+
+impl Payload {
+    pub fn counted(self, on_chunk: Arc<dyn Fn(usize) + Send + Sync>) -> Self {
+        Payload::Counted(Box::new(self), on_chunk)
+    }
+}
+
+impl Stream for Payload {
+    type Item = Result<Bytes, PayloadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            Payload::None => Poll::Ready(None),
+            Payload::H2(recv) => Pin::new(recv)
+                .poll_data(cx)
+                .map(|opt| opt.map(|res| res.map_err(PayloadError::from))),
+            Payload::Counted(inner, on_chunk) => {
+                let res = Pin::new(inner.as_mut()).poll_next(cx);
+                if let Poll::Ready(Some(Ok(ref bytes))) = res {
+                    on_chunk(bytes.len());
+                }
+                res
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PayloadError(h2::Error);
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "payload error: {}", self.0)
+    }
+}
+
+impl std::error::Error for PayloadError {}
+
+impl From<h2::Error> for PayloadError {
+    fn from(e: h2::Error) -> Self {
+        PayloadError(e)
+    }
+}